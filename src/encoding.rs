@@ -0,0 +1,220 @@
+//! Compact fixed-layout binary encoding for market-data records.
+//!
+//! JSON is convenient over the wire but wasteful on disk: every field name and
+//! timestamp is re-spelled on every row. For bulk storage and fast loads we
+//! instead pack each record into a fixed-size, little-endian byte layout with
+//! explicit offsets, so a history file is just a flat array of records that can
+//! be memory-mapped or streamed without a parser.
+//!
+//! Small categorical fields (exchange, base, quote, side) are stored as single
+//! byte codes resolved through a [`TryFrom<u8>`] table where `0` means
+//! None/invalid and any nonzero value selects a specific variant.
+
+use chrono::{DateTime, Utc};
+
+use crate::{Error, Side, TimeseriesDatum, Trade};
+
+/// Size in bytes of an encoded [`Trade`] record.
+///
+/// Layout (little-endian, offsets in bytes):
+///
+/// ```text
+/// 0  exchange : u8    market identity codes; 0 = None
+/// 1  base     : u8
+/// 2  quote    : u8
+/// 3  side     : u8    1 = Buy, 2 = Sell
+/// 4  server   : u64   coinapi receive time, milliseconds; 0 = absent
+/// 12 time     : u64   exchange time, nanoseconds
+/// 20 price    : f64
+/// 28 amount   : f64
+/// ```
+pub const TRADE_RECORD_LEN: usize = 36;
+
+/// Size in bytes of an encoded [`TimeseriesDatum`] record.
+///
+/// Layout (little-endian, offsets in bytes):
+///
+/// ```text
+/// 0  time_period_start : u64   nanoseconds
+/// 8  rate_open         : f64
+/// 16 rate_high         : f64
+/// 24 rate_low          : f64
+/// 32 rate_close        : f64
+/// ```
+pub const OHLC_RECORD_LEN: usize = 40;
+
+impl Side {
+    /// The single-byte code for this side. Never `0` — `0` is reserved for
+    /// None/invalid in the [`TryFrom<u8>`] table.
+    fn code(self) -> u8 {
+        match self {
+            Side::Buy => 1,
+            Side::Sell => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for Side {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Error> {
+        match value {
+            1 => Ok(Side::Buy),
+            2 => Ok(Side::Sell),
+            other => Err(Error::Decode(format!("invalid side code {other}"))),
+        }
+    }
+}
+
+/// Nanoseconds since the unix epoch, saturating on the `i64` overflow chrono
+/// would otherwise panic on.
+fn nanos(time: DateTime<Utc>) -> i64 {
+    time.timestamp_nanos_opt()
+        .unwrap_or(if time.timestamp() < 0 { i64::MIN } else { i64::MAX })
+}
+
+fn read_u64(buf: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(buf[off..off + 8].try_into().unwrap())
+}
+
+fn read_f64(buf: &[u8], off: usize) -> f64 {
+    f64::from_le_bytes(buf[off..off + 8].try_into().unwrap())
+}
+
+impl Trade {
+    /// Appends this trade to `out` as a [`TRADE_RECORD_LEN`]-byte record.
+    ///
+    /// [`Trade`] carries no market identity, so the exchange/base/quote code
+    /// slots are written as `0` (None); downstream writers that do track the
+    /// pair can overwrite those three leading bytes in place.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[0, 0, 0, self.side.code()]);
+        let server_ms = (nanos(self.time_coinapi) / 1_000_000) as u64;
+        out.extend_from_slice(&server_ms.to_le_bytes());
+        out.extend_from_slice(&(nanos(self.time_exchange) as u64).to_le_bytes());
+        out.extend_from_slice(&self.price.to_le_bytes());
+        out.extend_from_slice(&self.size.to_le_bytes());
+    }
+
+    /// Decodes a single trade from exactly [`TRADE_RECORD_LEN`] bytes.
+    pub fn decode(buf: &[u8]) -> Result<Self, Error> {
+        if buf.len() != TRADE_RECORD_LEN {
+            return Err(Error::Decode(format!(
+                "trade record must be {TRADE_RECORD_LEN} bytes, got {}",
+                buf.len()
+            )));
+        }
+        let side = Side::try_from(buf[3])?;
+        let time_coinapi =
+            DateTime::from_timestamp_nanos(read_u64(buf, 4) as i64 * 1_000_000);
+        let time_exchange = DateTime::from_timestamp_nanos(read_u64(buf, 12) as i64);
+        Ok(Trade {
+            time_exchange,
+            time_coinapi,
+            uuid: String::new(),
+            price: read_f64(buf, 20),
+            size: read_f64(buf, 28),
+            side,
+        })
+    }
+}
+
+impl TimeseriesDatum {
+    /// Appends this OHLC point to `out` as an [`OHLC_RECORD_LEN`]-byte record.
+    ///
+    /// Only the period-start timestamp is retained; the derived `time_period_end`,
+    /// `time_open`, and `time_close` are not stored and are reconstructed equal to
+    /// the period start on [`decode`](Self::decode).
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(nanos(self.time_period_start) as u64).to_le_bytes());
+        out.extend_from_slice(&self.rate_open.to_le_bytes());
+        out.extend_from_slice(&self.rate_high.to_le_bytes());
+        out.extend_from_slice(&self.rate_low.to_le_bytes());
+        out.extend_from_slice(&self.rate_close.to_le_bytes());
+    }
+
+    /// Decodes a single OHLC point from exactly [`OHLC_RECORD_LEN`] bytes.
+    pub fn decode(buf: &[u8]) -> Result<Self, Error> {
+        if buf.len() != OHLC_RECORD_LEN {
+            return Err(Error::Decode(format!(
+                "ohlc record must be {OHLC_RECORD_LEN} bytes, got {}",
+                buf.len()
+            )));
+        }
+        let time = DateTime::from_timestamp_nanos(read_u64(buf, 0) as i64);
+        Ok(TimeseriesDatum {
+            time_period_start: time,
+            time_period_end: time,
+            time_open: time,
+            time_close: time,
+            rate_open: read_f64(buf, 8),
+            rate_high: read_f64(buf, 16),
+            rate_low: read_f64(buf, 24),
+            rate_close: read_f64(buf, 32),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trade_round_trips() {
+        let trade = Trade {
+            time_exchange: DateTime::from_timestamp_nanos(1_600_000_000_123_456_789),
+            time_coinapi: DateTime::from_timestamp_nanos(1_600_000_000_999_000_000),
+            uuid: String::new(),
+            price: 42_000.5,
+            size: 0.125,
+            side: Side::Sell,
+        };
+        let mut buf = Vec::new();
+        trade.encode(&mut buf);
+        assert_eq!(buf.len(), TRADE_RECORD_LEN);
+
+        let back = Trade::decode(&buf).unwrap();
+        assert_eq!(back.time_exchange, trade.time_exchange);
+        assert_eq!(back.side, trade.side);
+        assert_eq!(back.price, trade.price);
+        assert_eq!(back.size, trade.size);
+        // server_time is stored downscaled to milliseconds
+        assert_eq!(back.time_coinapi, DateTime::from_timestamp_nanos(1_600_000_000_999_000_000));
+    }
+
+    #[test]
+    fn ohlc_round_trips() {
+        let start = DateTime::from_timestamp_nanos(1_600_000_000_000_000_000);
+        let datum = TimeseriesDatum {
+            time_period_start: start,
+            time_period_end: start,
+            time_open: start,
+            time_close: start,
+            rate_open: 1.0,
+            rate_high: 2.0,
+            rate_low: 0.5,
+            rate_close: 1.5,
+        };
+        let mut buf = Vec::new();
+        datum.encode(&mut buf);
+        assert_eq!(buf.len(), OHLC_RECORD_LEN);
+
+        let back = TimeseriesDatum::decode(&buf).unwrap();
+        assert_eq!(back.time_period_start, start);
+        assert_eq!(back.rate_open, 1.0);
+        assert_eq!(back.rate_close, 1.5);
+    }
+
+    #[test]
+    fn rejects_bad_length() {
+        assert!(Trade::decode(&[0u8; 16]).is_err());
+        assert!(TimeseriesDatum::decode(&[0u8; 16]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_side_code() {
+        let mut buf = vec![0u8; TRADE_RECORD_LEN];
+        buf[3] = 0; // None/invalid
+        assert!(Trade::decode(&buf).is_err());
+    }
+}
@@ -1,11 +1,14 @@
 //! Bindings to the [coinapi](https://www.coinapi.io/jq) cryptocurrency api
 //! Currently only the Market Data REST API is supported
 use chrono::{DateTime, NaiveDate, Utc};
+use futures::Stream;
 use std::time::Duration;
 
 use serde::{de::DeserializeOwned, Deserialize, Deserializer};
 use thiserror::Error;
 
+pub mod encoding;
+
 const API_KEY_ENV_NAME: &str = "COINAPI_KEY";
 
 #[derive(Error, Debug)]
@@ -19,6 +22,21 @@ pub enum Error {
     #[error("coinapi: {0}")]
     Api(String),
 
+    #[error("unauthorized (401): check the api key")]
+    Unauthorized,
+
+    #[error("forbidden (403): the key lacks access to this resource")]
+    Forbidden,
+
+    #[error("not found (404)")]
+    NotFound,
+
+    #[error("rate limited (429)")]
+    RateLimited { retry_after: Option<Duration> },
+
+    #[error("server error ({status}): {message}")]
+    Server { status: u16, message: String },
+
     #[error("json decode: {0}")]
     Json(#[from] serde_json::Error),
 
@@ -28,22 +46,117 @@ pub enum Error {
     #[error("api key not set (`{}` env)", API_KEY_ENV_NAME)]
     ApiKeyNotSet,
 
+    #[error("binary decode: {0}")]
+    Decode(String),
+
     #[error("{0}")]
     Other(String),
 }
 
+/// Number of times a throttled (HTTP 429) request is retried before giving up.
+const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Base URL of CoinAPI's production REST endpoint, including the `v1/` prefix.
+const PRODUCTION_BASE_URL: &str = "https://rest.coinapi.io/v1/";
+
+/// Base URL of CoinAPI's sandbox REST endpoint, including the `v1/` prefix.
+const SANDBOX_BASE_URL: &str = "https://rest-sandbox.coinapi.io/v1/";
+
 pub struct Coinapi {
     key: String,
     client: reqwest::Client,
+    base_url: String,
+    max_retries: u32,
 }
 
 impl Coinapi {
-    /// Tries to create a coinapi connection using the `COINAPI_KEY` as the api key
+    /// Tries to create a coinapi connection using the `COINAPI_KEY` as the api key.
+    ///
+    /// This is a thin wrapper over [`CoinapiBuilder`] pointed at the production endpoint.
     pub fn try_from_env() -> Result<Coinapi, Error> {
         let key = std::env::var(API_KEY_ENV_NAME).map_err(|_| Error::ApiKeyNotSet)?;
+        CoinapiBuilder::new().key(key).build()
+    }
+
+    /// Returns a builder for configuring the api key, endpoint, and underlying client.
+    pub fn builder() -> CoinapiBuilder {
+        CoinapiBuilder::new()
+    }
+}
+
+/// Builder for [`Coinapi`], used to point at the production or sandbox endpoint, override the
+/// base URL entirely, or supply a pre-configured [`reqwest::Client`].
+pub struct CoinapiBuilder {
+    key: Option<String>,
+    base_url: String,
+    client: Option<reqwest::Client>,
+    max_retries: u32,
+}
+
+impl Default for CoinapiBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CoinapiBuilder {
+    /// Creates a builder defaulting to the production endpoint and a fresh client.
+    pub fn new() -> Self {
+        CoinapiBuilder {
+            key: None,
+            base_url: PRODUCTION_BASE_URL.to_owned(),
+            client: None,
+            max_retries: DEFAULT_MAX_RETRIES,
+        }
+    }
+
+    /// Sets the api key to authenticate with.
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Points at CoinAPI's production endpoint (the default).
+    pub fn production(mut self) -> Self {
+        self.base_url = PRODUCTION_BASE_URL.to_owned();
+        self
+    }
+
+    /// Points at CoinAPI's sandbox endpoint.
+    pub fn sandbox(mut self) -> Self {
+        self.base_url = SANDBOX_BASE_URL.to_owned();
+        self
+    }
+
+    /// Overrides the base URL entirely, e.g. a regional endpoint or a local mock.
+    ///
+    /// The value should include the `v1/` path segment and a trailing slash, since routes are
+    /// appended to it directly.
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Supplies a custom [`reqwest::Client`] with the caller's own timeouts, proxy, etc.
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Sets how many times a throttled (HTTP 429) request is retried before giving up.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Builds the [`Coinapi`], failing if no api key was provided.
+    pub fn build(self) -> Result<Coinapi, Error> {
+        let key = self.key.ok_or(Error::ApiKeyNotSet)?;
         Ok(Coinapi {
             key,
-            client: reqwest::Client::new(),
+            client: self.client.unwrap_or_default(),
+            base_url: self.base_url,
+            max_retries: self.max_retries,
         })
     }
 }
@@ -237,6 +350,80 @@ where
     })
 }
 
+/// The side of a trade as seen from the taker.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub enum Side {
+    #[serde(rename = "BUY")]
+    Buy,
+    #[serde(rename = "SELL")]
+    Sell,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Trades(pub Vec<Trade>);
+
+/// A single executed trade as returned by the `trades` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct Trade {
+    #[serde(deserialize_with = "de_date_time")]
+    pub time_exchange: DateTime<Utc>,
+    #[serde(deserialize_with = "de_date_time")]
+    pub time_coinapi: DateTime<Utc>,
+    pub uuid: String,
+    pub price: f64,
+    pub size: f64,
+    #[serde(rename = "taker_side")]
+    pub side: Side,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Quotes(pub Vec<Quote>);
+
+/// A top-of-book quote as returned by the `quotes` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct Quote {
+    #[serde(deserialize_with = "de_date_time")]
+    pub time_exchange: DateTime<Utc>,
+    #[serde(deserialize_with = "de_date_time")]
+    pub time_coinapi: DateTime<Utc>,
+    pub ask_price: f64,
+    pub ask_size: f64,
+    pub bid_price: f64,
+    pub bid_size: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OrderBooks(pub Vec<OrderBook>);
+
+/// A level-2 order book snapshot as returned by the `orderbooks` endpoint.
+///
+/// Each level is a `(price, size)` pair, ordered as the exchange returns them
+/// (bids descending, asks ascending).
+#[derive(Debug, Deserialize)]
+pub struct OrderBook {
+    #[serde(deserialize_with = "de_date_time")]
+    pub time_exchange: DateTime<Utc>,
+    #[serde(deserialize_with = "de_date_time")]
+    pub time_coinapi: DateTime<Utc>,
+    #[serde(deserialize_with = "de_levels")]
+    pub bids: Vec<(f64, f64)>,
+    #[serde(deserialize_with = "de_levels")]
+    pub asks: Vec<(f64, f64)>,
+}
+
+fn de_levels<'de, D>(d: D) -> Result<Vec<(f64, f64)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Level {
+        price: f64,
+        size: f64,
+    }
+    let levels = Vec::<Level>::deserialize(d)?;
+    Ok(levels.into_iter().map(|l| (l.price, l.size)).collect())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Exchanges(pub Vec<Exchange>);
 
@@ -306,6 +493,18 @@ where
     }
 }
 
+/// Extracts CoinAPI's `X-RateLimit-Remaining` (requests left in the window) and
+/// `X-RateLimit-Reset` (seconds until the window resets) headers, ignoring any
+/// that are missing or malformed.
+fn rate_limit_headers(headers: &reqwest::header::HeaderMap) -> (Option<u64>, Option<Duration>) {
+    fn number(headers: &reqwest::header::HeaderMap, name: &str) -> Option<u64> {
+        headers.get(name)?.to_str().ok()?.parse().ok()
+    }
+    let remaining = number(headers, "X-RateLimit-Remaining");
+    let reset = number(headers, "X-RateLimit-Reset").map(Duration::from_secs);
+    (remaining, reset)
+}
+
 pub type Assets = Vec<Asset>;
 
 #[derive(Debug, Deserialize)]
@@ -364,28 +563,62 @@ impl Coinapi {
         T: DeserializeOwned,
     {
         let url = reqwest::Url::parse_with_params(
-            &format!("https://rest.coinapi.io/v1/{}", route.as_ref()),
+            &format!("{}{}", self.base_url, route.as_ref()),
             params,
         )?;
-        println!("Sending {url}");
-        let resp = self
-            .client
-            .get(url)
-            .header("X-CoinAPI-Key", &self.key)
-            .send()
-            .await?;
-
-        let json = resp.text().await?;
 
         #[derive(Deserialize)]
         struct ErrorRes {
             error: String,
         }
 
-        if let Ok(err) = serde_json::from_str::<ErrorRes>(&json) {
-            Err(Error::Api(err.error))
-        } else {
-            Ok(serde_json::from_str(&json)?)
+        let mut attempt = 0;
+        loop {
+            let resp = self
+                .client
+                .get(url.clone())
+                .header("X-CoinAPI-Key", &self.key)
+                .send()
+                .await?;
+
+            let status = resp.status();
+            let (_remaining, reset) = rate_limit_headers(resp.headers());
+
+            // CoinAPI throttles with HTTP 429; back off for the advertised reset
+            // window and retry up to `max_retries` times before surfacing the error.
+            if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                if attempt < self.max_retries {
+                    attempt += 1;
+                    let delay = reset.unwrap_or_else(|| Duration::from_secs(1));
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+                return Err(Error::RateLimited { retry_after: reset });
+            }
+
+            let json = resp.text().await?;
+
+            if status.is_success() {
+                // A 200 body can still carry an `{"error": ...}` payload.
+                return if let Ok(err) = serde_json::from_str::<ErrorRes>(&json) {
+                    Err(Error::Api(err.error))
+                } else {
+                    Ok(serde_json::from_str(&json)?)
+                };
+            }
+
+            let message = serde_json::from_str::<ErrorRes>(&json)
+                .map(|e| e.error)
+                .unwrap_or(json);
+            return Err(match status {
+                reqwest::StatusCode::UNAUTHORIZED => Error::Unauthorized,
+                reqwest::StatusCode::FORBIDDEN => Error::Forbidden,
+                reqwest::StatusCode::NOT_FOUND => Error::NotFound,
+                other => Error::Server {
+                    status: other.as_u16(),
+                    message,
+                },
+            });
         }
     }
 
@@ -420,6 +653,132 @@ impl Coinapi {
             .await?)
     }
 
+    /// Streams every point between `start` and `end`, transparently working around the
+    /// server's per-response cap.
+    ///
+    /// [`timeseries_data`](Self::timeseries_data) forwards `limit` verbatim and returns only
+    /// whatever a single request yields, silently truncating long ranges. This method instead
+    /// issues repeated requests, advancing `time_start` to the last returned `time_period_end`
+    /// until `end` is reached, and de-duplicates the boundary point shared between consecutive
+    /// pages. Returning a [`Stream`] lets multi-year pulls be processed incrementally rather
+    /// than buffered in memory all at once.
+    pub fn timeseries_data_all(
+        &self,
+        base: AssetName,
+        quote: AssetName,
+        period: Period,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> impl Stream<Item = Result<TimeseriesDatum, Error>> + '_ {
+        /// Points requested per page; CoinAPI caps a single response below this.
+        const PAGE_LIMIT: usize = 100_000;
+        async_stream::try_stream! {
+            let mut time_start = start;
+            let mut last_start: Option<DateTime<Utc>> = None;
+            loop {
+                let page = self
+                    .timeseries_data(base.clone(), quote.clone(), period, time_start, end, PAGE_LIMIT)
+                    .await?;
+                if page.0.is_empty() {
+                    break;
+                }
+                let mut next_start = None;
+                for datum in page.0 {
+                    // The next window re-requests from the last `time_period_end`, so the
+                    // first point(s) of a page can repeat ones already yielded; skip anything
+                    // at or before the last start we emitted.
+                    if last_start.is_some_and(|last| datum.time_period_start <= last) {
+                        continue;
+                    }
+                    last_start = Some(datum.time_period_start);
+                    next_start = Some(datum.time_period_end);
+                    yield datum;
+                }
+                match next_start {
+                    // Advance to the last period end; stop once we've covered the range or
+                    // a page yielded nothing new (no forward progress).
+                    Some(next_start) if next_start < end => time_start = next_start,
+                    _ => break,
+                }
+            }
+        }
+    }
+
+    /// Queries the `trades/{symbol_id}/history` endpoint for executed trades during a time
+    /// interval.
+    pub async fn trades_history(
+        &self,
+        symbol_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Trades, Error> {
+        let start = start.to_rfc3339();
+        let end = end.to_rfc3339();
+        let limit = limit.to_string();
+        Ok(self
+            .get(
+                format!("trades/{symbol_id}/history"),
+                [
+                    ("time_start", start.as_str()),
+                    ("time_end", &end),
+                    ("limit", &limit),
+                ]
+                .into_iter(),
+            )
+            .await?)
+    }
+
+    /// Queries the `quotes/{symbol_id}/history` endpoint for top-of-book quotes during a time
+    /// interval.
+    pub async fn quotes_history(
+        &self,
+        symbol_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Quotes, Error> {
+        let start = start.to_rfc3339();
+        let end = end.to_rfc3339();
+        let limit = limit.to_string();
+        Ok(self
+            .get(
+                format!("quotes/{symbol_id}/history"),
+                [
+                    ("time_start", start.as_str()),
+                    ("time_end", &end),
+                    ("limit", &limit),
+                ]
+                .into_iter(),
+            )
+            .await?)
+    }
+
+    /// Queries the `orderbooks/{symbol_id}/history` endpoint for level-2 order book snapshots
+    /// during a time interval.
+    pub async fn orderbook_history(
+        &self,
+        symbol_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<OrderBooks, Error> {
+        let start = start.to_rfc3339();
+        let end = end.to_rfc3339();
+        let limit = limit.to_string();
+        Ok(self
+            .get(
+                format!("orderbooks/{symbol_id}/history"),
+                [
+                    ("time_start", start.as_str()),
+                    ("time_end", &end),
+                    ("limit", &limit),
+                ]
+                .into_iter(),
+            )
+            .await?)
+    }
+
     /// Queries the `assets` endpoint to discover all assets supported by coinapi
     pub async fn assets(&self) -> Result<Assets, Error> {
         Ok(self.get("assets", [].into_iter()).await?)
@@ -536,6 +895,31 @@ mod tests {
             serde_json::from_str(include_str!("../test_files/exchanges.json")).unwrap();
     }
 
+    #[test]
+    fn trades_format() {
+        let trades: Trades =
+            serde_json::from_str(include_str!("../test_files/trades.json")).unwrap();
+        assert_eq!(trades.0[0].side, Side::Buy);
+        assert_eq!(trades.0[1].side, Side::Sell);
+    }
+
+    #[test]
+    fn quotes_format() {
+        let quotes: Quotes =
+            serde_json::from_str(include_str!("../test_files/quotes.json")).unwrap();
+        assert!(quotes.0[0].ask_price > quotes.0[0].bid_price);
+    }
+
+    #[test]
+    fn orderbooks_format() {
+        let books: OrderBooks =
+            serde_json::from_str(include_str!("../test_files/orderbooks.json")).unwrap();
+        assert_eq!(books.0[0].asks.len(), 3);
+        assert_eq!(books.0[0].bids.len(), 3);
+        // levels decode to (price, size) pairs
+        assert_eq!(books.0[0].asks[0], (16751.00, 1.5));
+    }
+
     fn crate_ci_api() -> Option<Coinapi> {
         if std::env::var("CI_TEST").is_ok() {
             // Only run on CI so we don't eat up api requests when spamming local testing